@@ -0,0 +1,16 @@
+use anyhow::anyhow;
+use std::{path::Path, process::Command};
+
+/// Spawns `ffmpeg` as a subprocess to transcode `input` into `output`
+pub fn convert(input: &Path, output: &Path, ffmpeg_args: &[String]) -> anyhow::Result<()> {
+    let mut command = Command::new("ffmpeg");
+    command.arg("-i").arg(input).args(ffmpeg_args).arg(output);
+
+    let result = command.output()?;
+    if result.status.success() {
+        Ok(())
+    } else {
+        // Return the command's error log, since it's the most useful diagnostic we have
+        Err(anyhow!(String::from_utf8_lossy(&result.stderr).to_string()))
+    }
+}
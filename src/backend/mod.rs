@@ -0,0 +1,28 @@
+use std::path::Path;
+
+mod cli;
+#[cfg(feature = "native-backend")]
+mod native;
+
+/// Selects which underlying implementation is used to transcode files.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Spawns the `ffmpeg` executable found on `PATH` once per file.
+    #[default]
+    Cli,
+    /// Transcodes in-process via the `ffmpeg-next` bindings to libavcodec, avoiding a subprocess
+    /// spawn per file. Only available when built with the `native-backend` feature.
+    #[cfg(feature = "native-backend")]
+    Native,
+}
+
+impl Backend {
+    /// Transcodes `input` into `output` using whichever backend was selected
+    pub fn convert(self, input: &Path, output: &Path, ffmpeg_args: &[String]) -> anyhow::Result<()> {
+        match self {
+            Self::Cli => cli::convert(input, output, ffmpeg_args),
+            #[cfg(feature = "native-backend")]
+            Self::Native => native::convert(input, output, ffmpeg_args),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context};
+use ffmpeg_next::frame::Audio as AudioFrame;
+use ffmpeg_sys_next::{
+    av_audio_fifo_alloc, av_audio_fifo_free, av_audio_fifo_read, av_audio_fifo_size,
+    av_audio_fifo_write, AVAudioFifo,
+};
+use std::{ffi::c_void, path::Path};
+
+/// Transcodes `input` into `output` in-process via the `ffmpeg-next`/`ffmpeg-sys-next` bindings
+/// to libavcodec, so errors surface as typed [`anyhow::Error`]s instead of decoded stderr bytes,
+/// and no subprocess is spawned per file
+pub fn convert(input: &Path, output: &Path, ffmpeg_args: &[String]) -> anyhow::Result<()> {
+    if !ffmpeg_args.is_empty() {
+        return Err(anyhow!(
+            "The native backend does not support extra ffmpeg arguments (got {:?}); pass --backend cli instead",
+            ffmpeg_args
+        ));
+    }
+
+    ffmpeg_next::init().context("Failed to initialize libavcodec")?;
+
+    let mut input_ctx = ffmpeg_next::format::input(input)
+        .with_context(|| format!("Failed to open input '{}'", input.display()))?;
+    let mut output_ctx = ffmpeg_next::format::output(output)
+        .with_context(|| format!("Failed to open output '{}'", output.display()))?;
+
+    let input_stream = input_ctx
+        .streams()
+        .best(ffmpeg_next::media::Type::Audio)
+        .ok_or_else(|| anyhow!("Input '{}' has no audio stream", input.display()))?;
+    let input_stream_index = input_stream.index();
+    let input_time_base = input_stream.time_base();
+
+    let mut decoder =
+        ffmpeg_next::codec::context::Context::from_parameters(input_stream.parameters())?
+            .decoder()
+            .audio()?;
+
+    // Let the output container tell us which codec it expects by default for this extension,
+    // rather than conflating the container extension with an encoder name
+    let codec_id = output_ctx.format().codec(output, ffmpeg_next::media::Type::Audio);
+    let encoder_codec = ffmpeg_next::encoder::find(codec_id)
+        .ok_or_else(|| anyhow!("No encoder found for output '{}'", output.display()))?;
+
+    // Not every encoder accepts the decoder's sample format (e.g. libopus wants S16 or FLT,
+    // AAC wants FLTP), so pick one the encoder actually supports and resample into it
+    let encoder_format = encoder_codec
+        .audio()
+        .and_then(|audio| audio.formats())
+        .and_then(|mut formats| formats.next())
+        .unwrap_or(decoder.format());
+
+    let mut encoder = ffmpeg_next::codec::context::Context::new_with_codec(encoder_codec)
+        .encoder()
+        .audio()?;
+    encoder.set_rate(decoder.rate() as i32);
+    encoder.set_channel_layout(decoder.channel_layout());
+    encoder.set_format(encoder_format);
+    let mut encoder = encoder.open_as(encoder_codec)?;
+    let encoder_time_base = encoder.time_base();
+
+    // Most fixed-frame-size encoders (libopus, AAC, ...) reject any frame that isn't exactly
+    // `frame_size` samples; 0 means the encoder accepts any size (AV_CODEC_CAP_VARIABLE_FRAME_SIZE)
+    let frame_size = if encoder.frame_size() > 0 {
+        encoder.frame_size() as usize
+    } else {
+        1024
+    };
+
+    let mut resampler = ffmpeg_next::software::resampler(
+        (decoder.format(), decoder.channel_layout(), decoder.rate()),
+        (encoder.format(), encoder.channel_layout(), encoder.rate()),
+    )?;
+
+    let mut fifo = AudioFifo::new(encoder.format(), encoder.channels(), frame_size)?;
+
+    let mut output_stream = output_ctx.add_stream(encoder_codec)?;
+    output_stream.set_parameters(&encoder);
+    let output_stream_index = output_stream.index();
+    let output_time_base = output_stream.time_base();
+
+    output_ctx.write_header()?;
+
+    let mut decoded = AudioFrame::empty();
+    let mut resampled = AudioFrame::empty();
+
+    for (stream, packet) in input_ctx.packets() {
+        if stream.index() != input_stream_index {
+            continue;
+        }
+
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            resampler.run(&decoded, &mut resampled)?;
+            fifo.push(&resampled)?;
+
+            while fifo.len() >= frame_size {
+                let chunk = fifo.pop(frame_size, encoder.format(), encoder.channel_layout(), encoder.rate())?;
+                encode_and_write(
+                    &mut encoder,
+                    Some(&chunk),
+                    &mut output_ctx,
+                    encoder_time_base,
+                    output_time_base,
+                    output_stream_index,
+                )?;
+            }
+        }
+    }
+
+    // Flush whatever frames are still buffered in the decoder, otherwise the tail of the audio
+    // is silently dropped
+    decoder.send_eof()?;
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        resampler.run(&decoded, &mut resampled)?;
+        fifo.push(&resampled)?;
+
+        while fifo.len() >= frame_size {
+            let chunk = fifo.pop(frame_size, encoder.format(), encoder.channel_layout(), encoder.rate())?;
+            encode_and_write(
+                &mut encoder,
+                Some(&chunk),
+                &mut output_ctx,
+                encoder_time_base,
+                output_time_base,
+                output_stream_index,
+            )?;
+        }
+    }
+
+    // The encoder accepts a final, shorter-than-`frame_size` frame immediately before EOF, so
+    // drain whatever's left in the FIFO instead of discarding it
+    let remaining = fifo.len();
+    if remaining > 0 {
+        let chunk = fifo.pop(remaining, encoder.format(), encoder.channel_layout(), encoder.rate())?;
+        encode_and_write(
+            &mut encoder,
+            Some(&chunk),
+            &mut output_ctx,
+            encoder_time_base,
+            output_time_base,
+            output_stream_index,
+        )?;
+    }
+
+    // Flush whatever packets are still buffered in the encoder (e.g. lookahead delay for opus)
+    encode_and_write(
+        &mut encoder,
+        None,
+        &mut output_ctx,
+        encoder_time_base,
+        output_time_base,
+        output_stream_index,
+    )?;
+
+    output_ctx.write_trailer()?;
+
+    Ok(())
+}
+
+/// Sends `frame` to the encoder (or, if `None`, signals end-of-stream), then drains and writes
+/// every packet it produces in response, rescaling timestamps from the encoder's time base to
+/// the output stream's
+fn encode_and_write(
+    encoder: &mut ffmpeg_next::encoder::Audio,
+    frame: Option<&AudioFrame>,
+    output_ctx: &mut ffmpeg_next::format::context::Output,
+    encoder_time_base: ffmpeg_next::Rational,
+    output_time_base: ffmpeg_next::Rational,
+    output_stream_index: usize,
+) -> anyhow::Result<()> {
+    match frame {
+        Some(frame) => encoder.send_frame(frame)?,
+        None => encoder.send_eof()?,
+    }
+
+    let mut encoded = ffmpeg_next::Packet::empty();
+    while encoder.receive_packet(&mut encoded).is_ok() {
+        encoded.set_stream(output_stream_index);
+        encoded.rescale_ts(encoder_time_base, output_time_base);
+        encoded.write_interleaved(output_ctx)?;
+    }
+
+    Ok(())
+}
+
+/// Re-chunks decoded/resampled samples into exactly `frame_size`-sample frames, since fixed
+/// frame size encoders (libopus, AAC, ...) reject any other size. Mirrors the `AVAudioFifo` usage
+/// in ffmpeg's own `transcode_aac.c` example, as ffmpeg-next does not wrap it safely.
+struct AudioFifo {
+    raw: *mut AVAudioFifo,
+}
+
+impl AudioFifo {
+    fn new(format: ffmpeg_next::format::Sample, channels: u16, frame_size: usize) -> anyhow::Result<Self> {
+        let raw = unsafe { av_audio_fifo_alloc(format.into(), channels as i32, frame_size as i32) };
+        if raw.is_null() {
+            return Err(anyhow!("Failed to allocate an audio FIFO for re-chunking"));
+        }
+        Ok(Self { raw })
+    }
+
+    fn len(&self) -> usize {
+        unsafe { av_audio_fifo_size(self.raw) as usize }
+    }
+
+    /// Appends every sample in `frame` to the FIFO
+    fn push(&mut self, frame: &AudioFrame) -> anyhow::Result<()> {
+        // Safety: `frame`'s `data` array outlives this call, and `av_audio_fifo_write` only reads
+        // `frame.samples()` samples from it
+        let data = unsafe { (*frame.as_ptr()).data.as_ptr() as *mut *mut c_void };
+        let ret = unsafe { av_audio_fifo_write(self.raw, data, frame.samples() as i32) };
+        if ret < 0 {
+            return Err(anyhow!("Failed to write samples into the audio FIFO"));
+        }
+        Ok(())
+    }
+
+    /// Pops exactly `count` samples into a freshly allocated frame; `count` must be <= `len()`
+    fn pop(
+        &mut self,
+        count: usize,
+        format: ffmpeg_next::format::Sample,
+        channel_layout: ffmpeg_next::channel_layout::ChannelLayout,
+        rate: u32,
+    ) -> anyhow::Result<AudioFrame> {
+        let mut frame = AudioFrame::new(format, count, channel_layout);
+        frame.set_rate(rate);
+
+        // Safety: `frame` was just allocated with room for exactly `count` samples per plane
+        let data = unsafe { (*frame.as_mut_ptr()).data.as_mut_ptr() as *mut *mut c_void };
+        let ret = unsafe { av_audio_fifo_read(self.raw, data, count as i32) };
+        if ret < 0 {
+            return Err(anyhow!("Failed to read samples from the audio FIFO"));
+        }
+
+        Ok(frame)
+    }
+}
+
+impl Drop for AudioFifo {
+    fn drop(&mut self) {
+        unsafe { av_audio_fifo_free(self.raw) };
+    }
+}
@@ -1,15 +1,44 @@
+mod backend;
+
 use anyhow::anyhow;
 use clap::Parser;
-use ignore::{types::TypesBuilder, DirEntry, WalkBuilder, WalkParallel, WalkState};
+use ignore::{types::Types, types::TypesBuilder, DirEntry, WalkBuilder, WalkParallel, WalkState};
+use indicatif::{ProgressBar, ProgressStyle};
 use std::{
     borrow::Cow,
     fmt::Display,
+    io::IsTerminal,
     ops::Deref,
     path::{Path, PathBuf},
-    process::Command,
-    sync::atomic::{AtomicU16, Ordering},
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering},
+    time::Duration,
 };
 
+/// Set by the Ctrl-C handler installed in [`Converter::run`], so in-progress work can wind down
+/// gracefully instead of the process being killed outright.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The process exit status to report once the walk has finished, borrowed from fd's
+/// `merge_exitcodes` pattern: workers fold the code of each failure or interruption into one
+/// shared value, and the most severe value wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+enum ExitCode {
+    Success,
+    GeneralError,
+    Sigint,
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(match code {
+            ExitCode::Success => 0,
+            ExitCode::GeneralError => 1,
+            ExitCode::Sigint => 130,
+        })
+    }
+}
+
 /// Recursively searches a given directory and its subdirectories for files with a given extension,
 /// and uses ffmpeg to convert those files to a different extension.
 ///
@@ -25,9 +54,6 @@ struct Args {
     /// The output file extension to which files will be converted.
     #[arg(short, long, default_value = "opus")]
     output: String,
-    /// The directory to search in.
-    #[arg(short, long, default_value = "./")]
-    target_dir: PathBuf,
     /// The maximum search depth. If unset, is infinite.
     #[arg(short, long)]
     max_depth: Option<usize>,
@@ -44,8 +70,30 @@ struct Args {
     #[arg(short, long)]
     preserve_files: bool,
     /// The file extensions to convert from.
-    #[arg(default_value = "mp3")]
+    #[arg(short, long = "input", default_value = "mp3")]
     inputs: Vec<String>,
+    /// The directories to search in, and/or individual files to convert. Directories are
+    /// searched recursively for files matching `inputs`; individual files are always converted,
+    /// regardless of their extension.
+    #[arg(default_value = "./")]
+    targets: Vec<PathBuf>,
+    /// If set, stops the entire search as soon as the first error occurs, instead of continuing
+    /// on to the remaining files.
+    #[arg(long)]
+    fail_fast: bool,
+    /// Which implementation to use to transcode files.
+    #[arg(long, value_enum, default_value = "cli")]
+    backend: backend::Backend,
+    /// If set, writes converted files into this directory instead of next to the originals,
+    /// mirroring each file's path relative to whichever target root it was found under, creating
+    /// any necessary intermediate directories. Implies `--preserve-files`.
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+    /// If set, skips files whose computed output already exists and is at least as new as the
+    /// input, instead of re-converting them. Makes repeated invocations over the same tree
+    /// resumable and idempotent.
+    #[arg(long)]
+    skip_existing: bool,
     /// Extra arguments to be passed to ffmpeg during execution.
     #[arg(raw = true)]
     ffmpeg_args: Vec<String>,
@@ -56,6 +104,9 @@ struct Converter {
     current_dir: Option<PathBuf>,
     ok_count: AtomicU16,
     err_count: AtomicU16,
+    skipped_count: AtomicU16,
+    exit_code: AtomicU8,
+    progress: ProgressBar,
 }
 
 impl Converter {
@@ -65,10 +116,36 @@ impl Converter {
             current_dir: std::env::current_dir().ok(),
             ok_count: Default::default(),
             err_count: Default::default(),
+            skipped_count: Default::default(),
+            exit_code: AtomicU8::new(ExitCode::Success as u8),
+            progress: ProgressBar::hidden(),
         }
     }
 
-    fn run(&mut self) -> anyhow::Result<()> {
+    /// Prints a log line, routing it through the progress bar when one is active so it doesn't
+    /// corrupt the rendered bar
+    fn log(&self, msg: impl Display) {
+        if self.progress.is_hidden() {
+            println!("{}", msg);
+        } else {
+            self.progress.println(msg.to_string());
+        }
+    }
+
+    /// Folds a newly observed exit code into the overall one, keeping whichever is more severe
+    fn record_exit_code(&self, code: ExitCode) {
+        self.exit_code.fetch_max(code as u8, Ordering::Relaxed);
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        match self.exit_code.load(Ordering::Relaxed) {
+            code if code == ExitCode::Sigint as u8 => ExitCode::Sigint,
+            code if code == ExitCode::GeneralError as u8 => ExitCode::GeneralError,
+            _ => ExitCode::Success,
+        }
+    }
+
+    fn run(&mut self) -> anyhow::Result<ExitCode> {
         if self.args.dry_run {
             println!("Dry-run enabled");
         }
@@ -79,20 +156,89 @@ impl Converter {
             self.args.output
         );
 
+        ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst))
+            .map_err(|err| anyhow!("Failed to set Ctrl-C handler: {}", err))?;
+
+        if !self.args.dry_run && std::io::stdout().is_terminal() {
+            self.setup_progress_bar()?;
+        }
+
         let walker = self.build_walker()?;
         walker.run(|| {
-            Box::new(|entry| match entry {
-                Ok(e) => self.try_convert_entry(&e),
-                Err(e) => self.handle_error(e),
+            Box::new(|entry| {
+                if INTERRUPTED.load(Ordering::SeqCst) {
+                    self.record_exit_code(ExitCode::Sigint);
+                    return WalkState::Quit;
+                }
+
+                match entry {
+                    Ok(e) => self.try_convert_entry(&e),
+                    Err(e) => self.handle_error(e),
+                }
             })
         });
 
+        self.progress.finish_and_clear();
+
         println!("Converted {} files.", self.ok_count.get_mut());
+        if self.args.skip_existing {
+            println!("Skipped {} already-converted files.", self.skipped_count.get_mut());
+        }
         println!("Finished with {} errors.", self.err_count.get_mut());
 
+        Ok(self.exit_code())
+    }
+
+    /// Counts the files matching up front (as a spinner, since this is itself a search of the
+    /// whole tree), then switches the progress bar to a determinate one tracking conversions
+    fn setup_progress_bar(&mut self) -> anyhow::Result<()> {
+        self.progress = ProgressBar::new_spinner();
+        self.progress.set_message("Counting files...");
+        self.progress.enable_steady_tick(Duration::from_millis(100));
+
+        let total = self.count_matching_files()?;
+
+        self.progress.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files",
+            )?
+            .progress_chars("#>-"),
+        );
+        self.progress.set_length(total);
+        self.progress.set_position(0);
+
         Ok(())
     }
 
+    /// Does a cheap, sequential pass over the targets to count the files that will be converted,
+    /// so the progress bar has a total to work towards
+    fn count_matching_files(&self) -> anyhow::Result<u64> {
+        let (first_target, rest_targets) = self.split_targets()?;
+
+        let mut builder = WalkBuilder::new(first_target);
+        for target in rest_targets {
+            builder.add(target);
+        }
+
+        let walker = builder
+            .standard_filters(false)
+            .max_depth(self.args.max_depth)
+            .follow_links(self.args.follow_links)
+            .same_file_system(self.args.same_fs)
+            .types(self.file_types()?)
+            .build();
+
+        Ok(walker
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .ok()
+                    .and_then(|e| e.file_type())
+                    .is_some_and(|t| t.is_file())
+            })
+            .count() as u64)
+    }
+
     fn format_input_args(&self) -> String {
         let mut result = String::new();
         if let Some((tail, head)) = self.args.inputs.split_last() {
@@ -108,27 +254,46 @@ impl Converter {
         // Use the user-specified number of threads, or the number of available CPU cores if unspecified
         let num_threads = self.args.num_threads.unwrap_or_else(num_cpus::get);
 
-        // Only match the files we want to convert
-        let mut file_types = TypesBuilder::new();
-        for input in &self.args.inputs {
-            file_types.add(input, &format!("*.{}", input))?;
+        // Seed the walk with the first target, then add the rest, so all of them are searched
+        let (first_target, rest_targets) = self.split_targets()?;
+
+        let mut builder = WalkBuilder::new(first_target);
+        for target in rest_targets {
+            builder.add(target);
         }
-        file_types.select("all");
-        let file_types = file_types.build()?;
 
         // Configure the directory iterator according to the user-specified args
-        Ok(WalkBuilder::new(&self.args.target_dir)
+        Ok(builder
             .standard_filters(false)
             .max_depth(self.args.max_depth)
             .follow_links(self.args.follow_links)
             .same_file_system(self.args.same_fs)
             .threads(num_threads)
-            .types(file_types)
+            .types(self.file_types()?)
             .build_parallel())
     }
 
+    /// Builds a matcher that only matches the file extensions we want to convert
+    fn file_types(&self) -> anyhow::Result<Types> {
+        let mut file_types = TypesBuilder::new();
+        for input in &self.args.inputs {
+            file_types.add(input, &format!("*.{}", input))?;
+        }
+        file_types.select("all");
+        Ok(file_types.build()?)
+    }
+
+    /// Splits the user-specified targets into the first (used to seed the `WalkBuilder`) and the rest
+    fn split_targets(&self) -> anyhow::Result<(&Path, &[PathBuf])> {
+        self.args
+            .targets
+            .split_first()
+            .map(|(first, rest)| (first.as_path(), rest))
+            .ok_or_else(|| anyhow!("No target directories or files were specified"))
+    }
+
     /// Transforms the input path into a form suitable for displaying
-    fn get_display_path<'a>(&'a self, path: &'a Path) -> impl Deref<Target = Path> + '_ {
+    fn get_display_path<'a>(&'a self, path: &'a Path) -> impl Deref<Target = Path> + 'a {
         self.current_dir
             .as_deref()
             .and_then(|base| pathdiff::diff_paths(path, base))
@@ -136,6 +301,62 @@ impl Converter {
             .map_or_else(|| Cow::Borrowed(path), Cow::Owned)
     }
 
+    /// Whether input files should be left in place rather than deleted after a successful
+    /// conversion; implied by `--output-dir`, since otherwise nothing would be left to mirror
+    fn keep_source_files(&self) -> bool {
+        self.args.preserve_files || self.args.output_dir.is_some()
+    }
+
+    /// Computes the path a converted file should be written to: next to `path` by default, or,
+    /// when `--output-dir` is set, at `path` mirrored under it relative to whichever target root
+    /// `path` was found under
+    fn compute_output_path(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        let output_path = path.with_extension(&self.args.output);
+
+        let Some(output_dir) = &self.args.output_dir else {
+            return Ok(output_path);
+        };
+
+        let root = self
+            .args
+            .targets
+            .iter()
+            .find(|target| path.starts_with(target))
+            .ok_or_else(|| anyhow!("'{}' is not under any of the search targets", path.display()))?;
+
+        // If the target itself is a file (rather than a directory we searched), there's no
+        // directory structure to mirror; diff against its parent so it lands directly in `output_dir`
+        let root = if root.is_dir() {
+            root.as_path()
+        } else {
+            root.parent().unwrap_or(Path::new(""))
+        };
+
+        let relative = pathdiff::diff_paths(&output_path, root).ok_or_else(|| {
+            anyhow!(
+                "Failed to compute a path for '{}' relative to '{}'",
+                output_path.display(),
+                root.display()
+            )
+        })?;
+
+        Ok(output_dir.join(relative))
+    }
+
+    /// When `--skip-existing` is set, checks whether `path`'s computed output already exists and
+    /// is at least as new as `path`, meaning the conversion can safely be skipped
+    fn should_skip(&self, path: &Path, output_path: &Path) -> bool {
+        let (Ok(output_meta), Ok(input_meta)) = (output_path.metadata(), path.metadata()) else {
+            return false;
+        };
+
+        match (output_meta.modified(), input_meta.modified()) {
+            (Ok(output_mtime), Ok(input_mtime)) => output_mtime >= input_mtime,
+            // If modification times aren't available on this platform, existence is enough
+            _ => true,
+        }
+    }
+
     fn try_convert_entry(&self, entry: &DirEntry) -> WalkState {
         if let Some(err) = entry.error() {
             return self.handle_error(err);
@@ -156,66 +377,98 @@ impl Converter {
 
         let path = entry.path();
 
-        println!("Converting '{}'", self.get_display_path(path).display());
+        if self.args.skip_existing {
+            match self.compute_output_path(path) {
+                Ok(output_path) if self.should_skip(path, &output_path) => {
+                    self.log(format!(
+                        "Skipping '{}' (output already exists)",
+                        self.get_display_path(path).display()
+                    ));
 
-        match self.try_convert_path(path) {
+                    self.skipped_count.fetch_add(1, Ordering::Relaxed);
+                    self.progress.inc(1);
+                    return WalkState::Continue;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    let result = self.handle_error(err);
+                    self.progress.inc(1);
+                    return result;
+                }
+            }
+        }
+
+        self.log(format!(
+            "Converting '{}'",
+            self.get_display_path(path).display()
+        ));
+
+        let result = match self.try_convert_path(path) {
             Ok(path) => {
-                println!(
+                self.log(format!(
                     "Finished converting '{}'",
                     self.get_display_path(&path).display()
-                );
+                ));
 
                 self.ok_count.fetch_add(1, Ordering::Relaxed);
                 WalkState::Continue
             }
             Err(err) => self.handle_error(err),
-        }
+        };
+
+        self.progress.inc(1);
+        result
     }
 
     fn try_convert_path(&self, path: &Path) -> anyhow::Result<PathBuf> {
-        let output_path = path.with_extension(&self.args.output);
-
-        let mut command = Command::new("ffmpeg");
-        command
-            .arg("-i")
-            .arg(path)
-            .args(&self.args.ffmpeg_args)
-            .arg(&output_path);
+        let output_path = self.compute_output_path(path)?;
 
         if self.args.dry_run {
             // On a dry-run, just print what we would do instead of actually doing it
-            println!("Dry_run: Running '{:?}'", command);
-            if !self.args.preserve_files {
-                println!(
+            self.log(format!(
+                "Dry_run: Would convert '{}' to '{}' using the {:?} backend",
+                self.get_display_path(path).display(),
+                self.get_display_path(&output_path).display(),
+                self.args.backend,
+            ));
+            if !self.keep_source_files() {
+                self.log(format!(
                     "Dry_run: Removing file '{}'",
                     self.get_display_path(path).display()
-                );
-            }
-            Ok(output_path)
-        } else {
-            // On a non-dry-run, actually run the command
-            let output = command.output()?;
-            if output.status.success() {
-                if !self.args.preserve_files {
-                    // Attempt to remove the input file if the command succeeded
-                    std::fs::remove_file(path)?;
-                }
-                Ok(output_path)
-            } else {
-                // If the command didn't succeed, don't remove the input file to avoid potential data loss,
-                // and return the command's error log
-                Err(anyhow!(String::from_utf8_lossy(&output.stderr).to_string()))
+                ));
             }
+            return Ok(output_path);
+        }
+
+        // On a non-dry-run, actually convert the file
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
+        self.args
+            .backend
+            .convert(path, &output_path, &self.args.ffmpeg_args)?;
+
+        if !self.keep_source_files() {
+            // Attempt to remove the input file if the conversion succeeded
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(output_path)
     }
 
     fn handle_error(&self, err: impl Display) -> WalkState {
         self.err_count.fetch_add(1, Ordering::Relaxed);
-        println!("{:#}", err);
-        WalkState::Quit
+        self.record_exit_code(ExitCode::GeneralError);
+        self.log(format!("{:#}", err));
+
+        if self.args.fail_fast {
+            WalkState::Quit
+        } else {
+            WalkState::Continue
+        }
     }
 }
 
-fn main() -> anyhow::Result<()> {
-    Converter::new(Args::parse()).run()
+fn main() -> anyhow::Result<std::process::ExitCode> {
+    Ok(Converter::new(Args::parse()).run()?.into())
 }